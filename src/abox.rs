@@ -3,64 +3,190 @@
     "C[x]", "r[x, y]", "(some r C)[x]", etc.
     This makes parsing easy without the loss of readability
 */
-use std::string;
-use common::{Individual, Relation, Concept, parse_concept};
+use std::fmt::Debug;
+use std::hash;
+use std::collections::HashSet;
 
-#[derive(Debug)]
-pub enum ABoxAxiom {
-    Concept(ConceptAxiom),
-    Relation(RelationAxiom)
+use common::{Individual, Relation, Concept, AtomicConcept};
+use lexer::{tokenize, Parser, ParseError, TokenKind, Token};
+
+pub trait ABoxAxiom: Debug + mopa::Any + ABoxAxiomClone {
+    fn axiom_type(&self) -> ABoxAxiomType;
+}
+
+pub trait ABoxAxiomClone {
+    fn clone_box(&self) -> Box<dyn ABoxAxiom>;
 }
 
-#[derive(Debug)]
+impl<T> ABoxAxiomClone for T where T: ABoxAxiom + Clone {
+    fn clone_box(&self) -> Box<dyn ABoxAxiom> { Box::new(self.clone()) }
+}
+
+// Same trick as Box<dyn Concept>: forward Clone to the concrete type.
+impl Clone for Box<dyn ABoxAxiom> {
+    fn clone(&self) -> Box<dyn ABoxAxiom> { self.clone_box() }
+}
+
+// No Display for ABox axioms yet either, so hash/compare by Debug output.
+impl PartialEq for Box<dyn ABoxAxiom> {
+    fn eq(&self, other: &Box<dyn ABoxAxiom>) -> bool {
+        format!("{:?}", self) == format!("{:?}", other)
+    }
+}
+
+impl Eq for Box<dyn ABoxAxiom> {}
+
+impl hash::Hash for Box<dyn ABoxAxiom> {
+    fn hash<H: hash::Hasher>(&self, hasher: &mut H) {
+        format!("{:?}", self).hash(hasher);
+    }
+}
+
+mopafy!(ABoxAxiom);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ABoxAxiomType { Concept, Relation }
+
+#[derive(Debug, Clone)]
 pub struct RelationAxiom {
     pub relation: Relation,
     pub lhs: Individual,
     pub rhs: Individual,
 }
 
-#[derive(Debug)]
+impl ABoxAxiom for RelationAxiom {
+    fn axiom_type(&self) -> ABoxAxiomType { ABoxAxiomType::Relation }
+}
+
+#[derive(Debug, Clone)]
 pub struct ConceptAxiom {
-    pub concept: Concept,
+    pub concept: Box<dyn Concept>,
     pub individual: Individual
 }
 
+impl ABoxAxiom for ConceptAxiom {
+    fn axiom_type(&self) -> ABoxAxiomType { ABoxAxiomType::Concept }
+}
+
+#[derive(Debug, Clone)]
+pub struct ABox {
+    pub axioms: HashSet<Box<dyn ABoxAxiom>>
+}
+
+impl ABox {
+    pub fn new() -> ABox {
+        ABox { axioms: HashSet::new() }
+    }
+}
 
-pub fn parse_abox(abox_str: &str) -> Vec<ABoxAxiom> {
-    let abox_str = abox_str.trim();
-    let mut abox_axioms = Vec::new();
+/// Parses every non-comment, non-blank line as an ABox axiom, collecting
+/// all parse errors instead of aborting on the first one.
+pub fn parse_abox(abox_str: &str) -> (ABox, Vec<ParseError>) {
+    let mut abox = ABox::new();
+    let mut errors = Vec::new();
 
-    for line in abox_str.lines() {
-        println!("Parsing line: {}", line);
-        abox_axioms.push(parse_abox_axiom(&line))
+    for line in abox_str.trim().lines() {
+        let line = line.trim();
+        if line.len() > 0 && !line.starts_with('#') {
+            match parse_abox_axiom(line) {
+                Ok(axiom) => { abox.axioms.insert(axiom); },
+                Err(e) => errors.push(e),
+            }
+        }
     }
 
-    abox_axioms
+    (abox, errors)
 }
 
+// An ABox axiom is a concept followed by a bracketed, comma-separated list
+// of individuals: "C[x]" for a concept assertion, "r[x, y]" for a relation
+// assertion (where the "concept" on the left must in fact be a plain name).
+pub fn parse_abox_axiom(axiom_str: &str) -> Result<Box<dyn ABoxAxiom>, ParseError> {
+    let tokens = tokenize(axiom_str)?;
+    let mut parser = Parser::new(&tokens);
+    let (concept_line, concept_column) = parser.peek().map(|t| (t.line, t.column)).unwrap_or((1, 1));
+    let concept = parser.parse_concept()?;
+    parser.expect(&TokenKind::LBracket)?;
+
+    let mut individuals = vec![Individual { name: parser.parse_ident()? }];
+    while let Some(&Token { kind: TokenKind::Comma, .. }) = parser.peek() {
+        parser.advance();
+        individuals.push(Individual { name: parser.parse_ident()? });
+    }
+
+    let rbracket = parser.expect(&TokenKind::RBracket)?;
+    let (rbracket_line, rbracket_column) = (rbracket.line, rbracket.column);
+
+    if !parser.is_at_end() {
+        let token = &parser.peek().unwrap();
+        return Err(ParseError {
+            message: "unexpected trailing tokens after ']'".to_string(),
+            line: token.line,
+            column: token.column,
+        });
+    }
+
+    match individuals.len() {
+        1 => Ok(Box::new(ConceptAxiom { concept: concept, individual: individuals.remove(0) })),
+        2 => {
+            let relation_name = concept.downcast_ref::<AtomicConcept>()
+                .ok_or_else(|| ParseError {
+                    message: "the left-hand side of a relation axiom must be a plain relation name".to_string(),
+                    line: concept_line,
+                    column: concept_column,
+                })?
+                .name()
+                .to_string();
+
+            Ok(Box::new(RelationAxiom {
+                relation: Relation { name: relation_name },
+                lhs: individuals.remove(0),
+                rhs: individuals.remove(0),
+            }))
+        },
+        n => Err(ParseError {
+            message: format!("expected 1 or 2 individuals in '[...]', found {}", n),
+            line: rbracket_line,
+            column: rbracket_column,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relation_axiom_keeps_individual_order() {
+        let axiom = parse_abox_axiom("r[x,y]").unwrap();
+        let relation_axiom = axiom.downcast_ref::<RelationAxiom>().unwrap();
+        assert_eq!(relation_axiom.lhs.name, "x");
+        assert_eq!(relation_axiom.rhs.name, "y");
+    }
+
+    #[test]
+    fn test_concept_axiom_individual() {
+        let axiom = parse_abox_axiom("C[x]").unwrap();
+        let concept_axiom = axiom.downcast_ref::<ConceptAxiom>().unwrap();
+        assert_eq!(concept_axiom.individual.name, "x");
+    }
+
+    #[test]
+    fn test_relation_axiom_requires_plain_relation_name() {
+        assert!(parse_abox_axiom("(not C)[x,y]").is_err());
+    }
+
+    #[test]
+    fn test_relation_axiom_error_points_at_the_concept_not_at_1_1() {
+        // Leading whitespace pushes the concept's '(' to column 2, so a
+        // hardcoded (1, 1) would be visibly wrong here.
+        let err = parse_abox_axiom(" (not C)[x,y]").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 2));
+    }
 
-pub fn parse_abox_axiom(axiom_str: &str) -> ABoxAxiom {
-    let axiom_str = axiom_str.trim();
-    let start_idx = axiom_str.find("[").unwrap_or(0);
-    let end_idx = axiom_str.find("]").unwrap_or(axiom_str.len());
-    let arguments_str = &axiom_str[start_idx..end_idx + 1].trim();
-    println!("arguments string: {}", arguments_str);
-    let mut individuals = arguments_str
-        .split(",").map(|n| (Individual {name: n.to_string()}))
-        .collect::<Vec<_>>();
-
-    if arguments_str.contains(",") {
-        // This is a relation axiom
-        ABoxAxiom::Relation(RelationAxiom {
-            relation: Relation { name: axiom_str[..start_idx].to_string() },
-            lhs: individuals.remove(0),
-            rhs: individuals.remove(1),
-        })
-    } else {
-        // This is a concept axiom
-        ABoxAxiom::Concept(ConceptAxiom {
-            concept: parse_concept(&axiom_str[..start_idx]),
-            individual: individuals.remove(0)
-        })
+    #[test]
+    fn test_trailing_tokens_error_points_at_the_extra_token() {
+        let err = parse_abox_axiom("C[x] extra").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 6));
     }
 }
@@ -1,7 +1,11 @@
+use std::fmt;
 use std::fmt::Debug;
 use std::clone::Clone;
 use std::any::{Any, TypeId};
 use std::marker::Sized;
+use std::hash;
+
+use lexer::{tokenize, Parser, ParseError};
 
 
 pub trait Concept: Debug + mopa::Any + ConceptClone {
@@ -26,42 +30,75 @@ impl Clone for Box<dyn Concept> {
     fn clone(&self) -> Box<dyn Concept> { self.clone_box() }
 }
 
+// There is no Display for concepts yet (see the pretty-printer work), so for
+// now we key set membership off of the Debug representation. Good enough to
+// tell two concepts apart structurally, which is all the tableau rules need.
+impl PartialEq for Box<dyn Concept> {
+    fn eq(&self, other: &Box<dyn Concept>) -> bool {
+        format!("{:?}", self) == format!("{:?}", other)
+    }
+}
+
+impl Eq for Box<dyn Concept> {}
+
+impl hash::Hash for Box<dyn Concept> {
+    fn hash<H: hash::Hasher>(&self, hasher: &mut H) {
+        format!("{:?}", self).hash(hasher);
+    }
+}
+
 mopafy!(Concept);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Relation { pub name: String }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Individual { pub name: String }
 
 #[derive(Debug, Clone)]
-pub struct AtomicConcept { name: String }
+pub struct AtomicConcept { pub(crate) name: String }
 
 #[derive(Debug, Clone)]
 pub struct NotConcept {
-    subconcept: Box<dyn Concept>
+    pub(crate) subconcept: Box<dyn Concept>
 }
 
 #[derive(Debug, Clone)]
 pub struct ConjunctionConcept {
-    subconcepts: Vec<Box<dyn Concept>>
+    pub(crate) subconcepts: Vec<Box<dyn Concept>>
 }
 
 #[derive(Debug, Clone)]
 pub struct DisjunctionConcept {
-    subconcepts: Vec<Box<dyn Concept>>
+    pub(crate) subconcepts: Vec<Box<dyn Concept>>
 }
 
 #[derive(Debug, Clone)]
 pub struct OnlyConcept {
-    subconcept: Box<dyn Concept>,
-    relation: Relation
+    pub(crate) subconcept: Box<dyn Concept>,
+    pub(crate) relation: Relation
 }
 
 #[derive(Debug, Clone)]
 pub struct SomeConcept {
-    subconcept: Box<dyn Concept>,
-    relation: Relation
+    pub(crate) subconcept: Box<dyn Concept>,
+    pub(crate) relation: Relation
+}
+
+// Qualified number restrictions: "at least n r-successors in C" / "at most
+// n r-successors in C". Together with ALC this gives ALCQ.
+#[derive(Debug, Clone)]
+pub struct MinCardinality {
+    pub(crate) n: usize,
+    pub(crate) relation: Relation,
+    pub(crate) subconcept: Box<dyn Concept>
+}
+
+#[derive(Debug, Clone)]
+pub struct MaxCardinality {
+    pub(crate) n: usize,
+    pub(crate) relation: Relation,
+    pub(crate) subconcept: Box<dyn Concept>
 }
 
 impl Concept for AtomicConcept {
@@ -70,6 +107,10 @@ impl Concept for AtomicConcept {
     }
 }
 
+impl AtomicConcept {
+    pub fn name(&self) -> &str { &self.name }
+}
+
 impl Concept for NotConcept {
     fn convert_to_nnf(&self) -> Box<dyn Concept> {
         if self.subconcept.is::<AtomicConcept>() {
@@ -111,6 +152,34 @@ impl Concept for NotConcept {
                 relation: subconcept.relation.clone(),
                 subconcept: subconcept.subconcept.negate().convert_to_nnf()
             })
+        } else if self.subconcept.is::<MinCardinality>() {
+            // not [>= n r C] => <= (n-1) r C
+            let subconcept = self.subconcept.downcast_ref::<MinCardinality>().unwrap();
+            if subconcept.n == 0 {
+                // not [>= 0 r C] is unsatisfiable: every individual
+                // vacuously has at least zero r-successors in C, so there
+                // is no "<= -1" to fall back to -- force a clash instead.
+                Box::new(ConjunctionConcept {
+                    subconcepts: vec![
+                        Box::new(AtomicConcept { name: "_Bottom".to_string() }) as Box<dyn Concept>,
+                        Box::new(NotConcept { subconcept: Box::new(AtomicConcept { name: "_Bottom".to_string() }) }) as Box<dyn Concept>,
+                    ]
+                })
+            } else {
+                Box::new(MaxCardinality {
+                    n: subconcept.n - 1,
+                    relation: subconcept.relation.clone(),
+                    subconcept: subconcept.subconcept.convert_to_nnf()
+                })
+            }
+        } else if self.subconcept.is::<MaxCardinality>() {
+            // not [<= n r C] => >= (n+1) r C
+            let subconcept = self.subconcept.downcast_ref::<MaxCardinality>().unwrap();
+            Box::new(MinCardinality {
+                n: subconcept.n + 1,
+                relation: subconcept.relation.clone(),
+                subconcept: subconcept.subconcept.convert_to_nnf()
+            })
         } else {
             unimplemented!();
         }
@@ -118,6 +187,11 @@ impl Concept for NotConcept {
 
     }
 }
+
+impl NotConcept {
+    pub fn subconcept(&self) -> &Box<dyn Concept> { &self.subconcept }
+}
+
 impl Concept for ConjunctionConcept {
     fn convert_to_nnf(&self) -> Box<dyn Concept> {
         Box::new(ConjunctionConcept {
@@ -125,6 +199,11 @@ impl Concept for ConjunctionConcept {
         })
     }
 }
+
+impl ConjunctionConcept {
+    pub fn subconcepts(&self) -> &Vec<Box<dyn Concept>> { &self.subconcepts }
+}
+
 impl Concept for DisjunctionConcept {
     fn convert_to_nnf(&self) -> Box<dyn Concept> {
         Box::new(DisjunctionConcept {
@@ -132,6 +211,11 @@ impl Concept for DisjunctionConcept {
         })
     }
 }
+
+impl DisjunctionConcept {
+    pub fn subconcepts(&self) -> &Vec<Box<dyn Concept>> { &self.subconcepts }
+}
+
 impl Concept for OnlyConcept {
     fn convert_to_nnf(&self) -> Box<dyn Concept> {
         Box::new(OnlyConcept {
@@ -140,6 +224,12 @@ impl Concept for OnlyConcept {
         })
     }
 }
+
+impl OnlyConcept {
+    pub fn relation(&self) -> &Relation { &self.relation }
+    pub fn subconcept(&self) -> &Box<dyn Concept> { &self.subconcept }
+}
+
 impl Concept for SomeConcept {
     fn convert_to_nnf(&self) -> Box<dyn Concept> {
         Box::new(SomeConcept {
@@ -149,89 +239,111 @@ impl Concept for SomeConcept {
     }
 }
 
+impl SomeConcept {
+    pub fn relation(&self) -> &Relation { &self.relation }
+    pub fn subconcept(&self) -> &Box<dyn Concept> { &self.subconcept }
+}
 
-pub fn parse_concept(concept_str: &str) -> Box<dyn Concept> {
-    // Parses concept or panics if the string is not a correct concept
-    // let mut words = concept_str.split(' ').collect();
-    let concept_str = concept_str.trim();
+impl Concept for MinCardinality {
+    fn convert_to_nnf(&self) -> Box<dyn Concept> {
+        Box::new(MinCardinality {
+            n: self.n,
+            relation: self.relation.clone(),
+            subconcept: self.subconcept.convert_to_nnf()
+        })
+    }
+}
 
-    println!("Parsing concept: {}", concept_str);
+impl MinCardinality {
+    pub fn n(&self) -> usize { self.n }
+    pub fn relation(&self) -> &Relation { &self.relation }
+    pub fn subconcept(&self) -> &Box<dyn Concept> { &self.subconcept }
+}
 
-    if &concept_str[..1] == "(" {
-        // Our concept is wrapped up into brackets "(..)"
-        parse_concept(&concept_str[1..(concept_str.len() - 1)])
-    } else if concept_str.len() > 3 && &concept_str[..3] == "and" {
-        // println!("It is and!");
-        Box::new(ConjunctionConcept { subconcepts: extract_concepts(&concept_str[3..]) })
-    } else if concept_str.len() > 2 && &concept_str[..2] == "or" {
-        // println!("It is or!");
-        Box::new(DisjunctionConcept { subconcepts: extract_concepts(&concept_str[2..]) })
-    } else if concept_str.len() > 4 && &concept_str[..4] == "only" {
-        // println!("It is only!");
-        Box::new(OnlyConcept {
-            relation: Relation {name: concept_str.chars().nth(5).unwrap().to_string()},
-            subconcept: parse_concept(&concept_str[6..])
-        })
-    } else if concept_str.len() > 4 && &concept_str[..4] == "some" {
-        // println!("It is some!");
-        Box::new(SomeConcept {
-            relation: Relation {name: concept_str.chars().nth(5).unwrap().to_string()},
-            subconcept: parse_concept(&concept_str[6..])
+impl Concept for MaxCardinality {
+    fn convert_to_nnf(&self) -> Box<dyn Concept> {
+        Box::new(MaxCardinality {
+            n: self.n,
+            relation: self.relation.clone(),
+            subconcept: self.subconcept.convert_to_nnf()
         })
-    } else if concept_str.len() > 3 && &concept_str[..3] == "not" {
-        println!("It is not!");
-        Box::new(NotConcept { subconcept: parse_concept(&concept_str[3..]) })
-    } else {
-        println!("It is an atomic concept!");
-        // This is an Atomic Concept!
-        Box::new(AtomicConcept { name: concept_str.to_string() })
     }
 }
 
+impl MaxCardinality {
+    pub fn n(&self) -> usize { self.n }
+    pub fn relation(&self) -> &Relation { &self.relation }
+    pub fn subconcept(&self) -> &Box<dyn Concept> { &self.subconcept }
+}
 
-fn extract_concepts(concepts_str: &str) -> Vec<Box<dyn Concept>> {
-    // Takes a concepts string, seperated by whitespace and wrapped up in brackets,
-    // parses them individually and returns a vector of concepts.
-    let concepts_str = concepts_str.trim();
-    println!("Extractinc concepts: {}", concepts_str);
-    let mut concepts: Vec<Box<dyn Concept>> = Vec::new();
-    let mut curr_depth = 0;
-    let mut curr_concept_start_idx = 0;
-    let mut i = 0;
 
-    while i < concepts_str.len() {
-        if &concepts_str[i..i + 1] == "(" {
-            curr_depth += 1; // Going a level deeper
-        } else if &concepts_str[i..i + 1] == ")" {
-            curr_depth -= 1; // Going a level out
-        }
+pub fn parse_concept(concept_str: &str) -> Result<Box<dyn Concept>, ParseError> {
+    let tokens = tokenize(concept_str)?;
+    let mut parser = Parser::new(&tokens);
+    parser.parse_concept_to_end()
+}
 
-        if curr_depth == 0 {
-            println!("Found concept: {}", &concepts_str[curr_concept_start_idx .. i + 1]);
-            concepts.push(parse_concept(&concepts_str[curr_concept_start_idx .. i + 1]));
-            curr_concept_start_idx = i + 1; // Next concept starts on the next character
-            i += 1;
-        }
+// Pretty-printing: re-emits the crate's surface syntax for a concept tree,
+// inserting brackets only around the operands of "and"/"or" -- that's the
+// only place `extract_concepts` requires them to find sibling boundaries.
+// Everywhere else (a "not"/"only"/"some" filler) the rest of the string is
+// unambiguously the sub-concept, so no wrapping is needed to round-trip.
+fn fmt_concept(concept: &dyn Concept) -> String {
+    if let Some(c) = concept.downcast_ref::<AtomicConcept>() {
+        c.name().to_string()
+    } else if let Some(c) = concept.downcast_ref::<NotConcept>() {
+        format!("not {}", fmt_concept(&**c.subconcept()))
+    } else if let Some(c) = concept.downcast_ref::<ConjunctionConcept>() {
+        format!("and {}", fmt_operands(c.subconcepts()))
+    } else if let Some(c) = concept.downcast_ref::<DisjunctionConcept>() {
+        format!("or {}", fmt_operands(c.subconcepts()))
+    } else if let Some(c) = concept.downcast_ref::<OnlyConcept>() {
+        format!("only {} {}", c.relation().name, fmt_concept(&**c.subconcept()))
+    } else if let Some(c) = concept.downcast_ref::<SomeConcept>() {
+        format!("some {} {}", c.relation().name, fmt_concept(&**c.subconcept()))
+    } else if let Some(c) = concept.downcast_ref::<MinCardinality>() {
+        format!(">= {} {} {}", c.n(), c.relation().name, fmt_concept(&**c.subconcept()))
+    } else if let Some(c) = concept.downcast_ref::<MaxCardinality>() {
+        format!("<= {} {} {}", c.n(), c.relation().name, fmt_concept(&**c.subconcept()))
+    } else {
+        unimplemented!()
+    }
+}
+
+fn fmt_operands(subconcepts: &[Box<dyn Concept>]) -> String {
+    subconcepts.iter()
+        .map(|c| format!("({})", fmt_concept(&**c)))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
 
-        i += 1;
+impl fmt::Display for dyn Concept {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", fmt_concept(self))
     }
-    // for (i, c) in concepts_str.chars().enumerate() {
-    //     if c == '(' {
-    //         curr_depth += 1; // Going a level deeper
-    //     } else if c == ')' {
-    //         curr_depth -= 1; // Going a level out
-    //     }
+}
 
-    //     if curr_depth == 0 {
-    //         println!("Found concept: {}", &concepts_str[curr_concept_start_idx..i+1]);
-    //         concepts.push(parse_concept(&concepts_str[curr_concept_start_idx..i+1]));
-    //         curr_concept_start_idx = i; // Next concept starts on the next character
-    //     }
-    // }
+// Indented multi-line mode: same grammar, but "and"/"or" put each operand on
+// its own line so large aggregated GCIs and expanded definitions stay
+// readable. Everything else still prints on one line.
+pub fn format_concept_indented(concept: &dyn Concept, depth: usize) -> String {
+    let tab = "  ".repeat(depth);
 
-    debug_assert!(concepts.len() > 0);
+    if let Some(c) = concept.downcast_ref::<ConjunctionConcept>() {
+        format!("and\n{}", fmt_operands_indented(c.subconcepts(), depth))
+    } else if let Some(c) = concept.downcast_ref::<DisjunctionConcept>() {
+        format!("or\n{}", fmt_operands_indented(c.subconcepts(), depth))
+    } else {
+        format!("{}{}", tab, fmt_concept(concept))
+    }
+}
 
-    concepts
+fn fmt_operands_indented(subconcepts: &[Box<dyn Concept>], depth: usize) -> String {
+    let tab = "  ".repeat(depth + 1);
+    subconcepts.iter()
+        .map(|c| format!("{}({})", tab, format_concept_indented(&**c, depth + 1).trim_start()))
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
 #[cfg(test)]
@@ -239,7 +351,52 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_extract_concepts() {
-        assert_eq!(extract_concepts("C"), vec![AtomicConcept {name: "C"}]);
+    fn test_parse_concept_round_trips_through_display() {
+        let concept = parse_concept("and (A) (not B) (some r C)").unwrap();
+        assert_eq!(concept.to_string(), "and (A) (not B) (some r C)");
+    }
+
+    #[test]
+    fn test_parse_concept_reports_error_instead_of_panicking() {
+        assert!(parse_concept("and (A) (B").is_err());
+    }
+
+    #[test]
+    fn test_parse_min_and_max_cardinality() {
+        let min = parse_concept(">= 2 r C").unwrap();
+        assert_eq!(min.to_string(), ">= 2 r C");
+
+        let max = parse_concept("<= 1 r C").unwrap();
+        assert_eq!(max.to_string(), "<= 1 r C");
+    }
+
+    #[test]
+    fn test_not_min_cardinality_converts_to_max_cardinality_minus_one() {
+        let concept = NotConcept { subconcept: Box::new(MinCardinality {
+            n: 2, relation: Relation { name: "r".to_string() }, subconcept: Box::new(AtomicConcept { name: "C".to_string() }),
+        }) };
+        let nnf = concept.convert_to_nnf();
+        assert_eq!(nnf.to_string(), "<= 1 r C");
+    }
+
+    #[test]
+    fn test_not_max_cardinality_converts_to_min_cardinality_plus_one() {
+        let concept = NotConcept { subconcept: Box::new(MaxCardinality {
+            n: 1, relation: Relation { name: "r".to_string() }, subconcept: Box::new(AtomicConcept { name: "C".to_string() }),
+        }) };
+        let nnf = concept.convert_to_nnf();
+        assert_eq!(nnf.to_string(), ">= 2 r C");
+    }
+
+    #[test]
+    fn test_not_min_cardinality_zero_forces_a_clash() {
+        // "not [>= 0 r C]" is unsatisfiable: every individual vacuously has
+        // at least zero r-successors, so there is no "<= -1" fallback.
+        let concept = NotConcept { subconcept: Box::new(MinCardinality {
+            n: 0, relation: Relation { name: "r".to_string() }, subconcept: Box::new(AtomicConcept { name: "C".to_string() }),
+        }) };
+        let nnf = concept.convert_to_nnf();
+        let conjunction = nnf.downcast_ref::<ConjunctionConcept>().unwrap();
+        assert_eq!(conjunction.subconcepts().len(), 2);
     }
 }
\ No newline at end of file
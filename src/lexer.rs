@@ -0,0 +1,344 @@
+/*
+    Shared tokenizer + recursive-descent concept parser used by the concept,
+    ABox and TBox parsers. Splitting this out means none of those parsers
+    slice strings by byte offset any more, so multi-character relation/
+    individual names and non-ASCII identifiers just work, and a malformed
+    input produces a `ParseError` with a line/column instead of a panic.
+*/
+use std::fmt;
+
+use common::{Concept, AtomicConcept, NotConcept, ConjunctionConcept, DisjunctionConcept, OnlyConcept, SomeConcept,
+             MinCardinality, MaxCardinality, Relation};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    And,
+    Or,
+    Some,
+    Only,
+    Not,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    DoubleEquals,
+    Arrow,
+    GreaterEquals,
+    LessEquals,
+    Ident(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut column = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\n' {
+            line += 1;
+            column = 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+            column += 1;
+        } else if c == '(' {
+            tokens.push(Token { kind: TokenKind::LParen, line, column });
+            i += 1;
+            column += 1;
+        } else if c == ')' {
+            tokens.push(Token { kind: TokenKind::RParen, line, column });
+            i += 1;
+            column += 1;
+        } else if c == '[' {
+            tokens.push(Token { kind: TokenKind::LBracket, line, column });
+            i += 1;
+            column += 1;
+        } else if c == ']' {
+            tokens.push(Token { kind: TokenKind::RBracket, line, column });
+            i += 1;
+            column += 1;
+        } else if c == ',' {
+            tokens.push(Token { kind: TokenKind::Comma, line, column });
+            i += 1;
+            column += 1;
+        } else if c == '=' && chars.get(i + 1) == Some(&'=') {
+            tokens.push(Token { kind: TokenKind::DoubleEquals, line, column });
+            i += 2;
+            column += 2;
+        } else if c == '-' && chars.get(i + 1) == Some(&'>') {
+            tokens.push(Token { kind: TokenKind::Arrow, line, column });
+            i += 2;
+            column += 2;
+        } else if c == '>' && chars.get(i + 1) == Some(&'=') {
+            tokens.push(Token { kind: TokenKind::GreaterEquals, line, column });
+            i += 2;
+            column += 2;
+        } else if c == '<' && chars.get(i + 1) == Some(&'=') {
+            tokens.push(Token { kind: TokenKind::LessEquals, line, column });
+            i += 2;
+            column += 2;
+        } else if is_ident_char(c) {
+            let start_column = column;
+            let start = i;
+            while i < chars.len() && is_ident_char(chars[i]) {
+                i += 1;
+                column += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            let kind = match word.as_str() {
+                "and" => TokenKind::And,
+                "or" => TokenKind::Or,
+                "some" => TokenKind::Some,
+                "only" => TokenKind::Only,
+                "not" => TokenKind::Not,
+                _ => TokenKind::Ident(word),
+            };
+            tokens.push(Token { kind, line, column: start_column });
+        } else {
+            return Err(ParseError {
+                message: format!("unexpected character '{}'", c),
+                line, column,
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive-descent parser over a token slice. Concept grammar is shared by
+/// `common::parse_concept`; `abox`/`tbox` build their own axiom grammar on
+/// top of the generic token helpers (`peek`/`advance`/`expect`).
+pub struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Parser<'a> {
+        Parser { tokens, pos: 0 }
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    pub fn advance(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn eof_error(&self, expected: &str) -> ParseError {
+        let (line, column) = self.tokens.last().map(|t| (t.line, t.column + 1)).unwrap_or((1, 1));
+        ParseError { message: format!("expected {}, found end of input", expected), line, column }
+    }
+
+    pub fn expect(&mut self, kind: &TokenKind) -> Result<&Token, ParseError> {
+        match self.peek() {
+            Some(token) if &token.kind == kind => {
+                self.pos += 1;
+                Ok(&self.tokens[self.pos - 1])
+            },
+            Some(token) => Err(ParseError {
+                message: format!("expected {:?}, found {:?}", kind, token.kind),
+                line: token.line,
+                column: token.column,
+            }),
+            None => Err(self.eof_error(&format!("{:?}", kind))),
+        }
+    }
+
+    pub fn parse_ident(&mut self) -> Result<String, ParseError> {
+        match self.advance() {
+            Some(&Token { kind: TokenKind::Ident(ref name), .. }) => Ok(name.clone()),
+            Some(token) => Err(ParseError {
+                message: format!("expected an identifier, found {:?}", token.kind),
+                line: token.line,
+                column: token.column,
+            }),
+            None => Err(self.eof_error("an identifier")),
+        }
+    }
+
+    pub fn parse_number(&mut self) -> Result<usize, ParseError> {
+        match self.advance() {
+            Some(&Token { kind: TokenKind::Ident(ref digits), line, column }) => {
+                digits.parse::<usize>().map_err(|_| ParseError {
+                    message: format!("expected a non-negative integer, found '{}'", digits),
+                    line, column,
+                })
+            },
+            Some(token) => Err(ParseError {
+                message: format!("expected a non-negative integer, found {:?}", token.kind),
+                line: token.line,
+                column: token.column,
+            }),
+            None => Err(self.eof_error("a non-negative integer")),
+        }
+    }
+
+    /// Parses a single concept. Does not require the whole token stream to
+    /// be consumed -- callers that need that should check `is_at_end`.
+    pub fn parse_concept(&mut self) -> Result<Box<dyn Concept>, ParseError> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err(self.eof_error("a concept")),
+        };
+
+        match token.kind {
+            TokenKind::LParen => {
+                self.advance();
+                let concept = self.parse_concept()?;
+                self.expect(&TokenKind::RParen)?;
+                Ok(concept)
+            },
+            TokenKind::And => {
+                self.advance();
+                Ok(Box::new(ConjunctionConcept { subconcepts: self.parse_operands()? }))
+            },
+            TokenKind::Or => {
+                self.advance();
+                Ok(Box::new(DisjunctionConcept { subconcepts: self.parse_operands()? }))
+            },
+            TokenKind::Only => {
+                self.advance();
+                let relation = Relation { name: self.parse_ident()? };
+                let subconcept = self.parse_concept()?;
+                Ok(Box::new(OnlyConcept { relation, subconcept }))
+            },
+            TokenKind::Some => {
+                self.advance();
+                let relation = Relation { name: self.parse_ident()? };
+                let subconcept = self.parse_concept()?;
+                Ok(Box::new(SomeConcept { relation, subconcept }))
+            },
+            TokenKind::Not => {
+                self.advance();
+                let subconcept = self.parse_concept()?;
+                Ok(Box::new(NotConcept { subconcept }))
+            },
+            TokenKind::GreaterEquals => {
+                self.advance();
+                let n = self.parse_number()?;
+                let relation = Relation { name: self.parse_ident()? };
+                let subconcept = self.parse_concept()?;
+                Ok(Box::new(MinCardinality { n, relation, subconcept }))
+            },
+            TokenKind::LessEquals => {
+                self.advance();
+                let n = self.parse_number()?;
+                let relation = Relation { name: self.parse_ident()? };
+                let subconcept = self.parse_concept()?;
+                Ok(Box::new(MaxCardinality { n, relation, subconcept }))
+            },
+            TokenKind::Ident(name) => {
+                self.advance();
+                Ok(Box::new(AtomicConcept { name }))
+            },
+            _ => Err(ParseError {
+                message: format!("unexpected token {:?}", token.kind),
+                line: token.line,
+                column: token.column,
+            }),
+        }
+    }
+
+    // "and"/"or" operands are always wrapped in parens -- this is what lets
+    // the parser (and the pretty-printer) find sibling boundaries without
+    // counting bytes.
+    fn parse_operands(&mut self) -> Result<Vec<Box<dyn Concept>>, ParseError> {
+        let mut operands = Vec::new();
+
+        while let Some(&Token { kind: TokenKind::LParen, .. }) = self.peek() {
+            self.advance();
+            operands.push(self.parse_concept()?);
+            self.expect(&TokenKind::RParen)?;
+        }
+
+        if operands.is_empty() {
+            return Err(match self.peek() {
+                Some(token) => ParseError {
+                    message: "expected at least one parenthesized operand".to_string(),
+                    line: token.line,
+                    column: token.column,
+                },
+                None => self.eof_error("a parenthesized operand"),
+            });
+        }
+
+        Ok(operands)
+    }
+
+    /// Parses a concept and fails if any tokens are left over afterwards.
+    pub fn parse_concept_to_end(&mut self) -> Result<Box<dyn Concept>, ParseError> {
+        let concept = self.parse_concept()?;
+        if !self.is_at_end() {
+            let token = &self.tokens[self.pos];
+            return Err(ParseError {
+                message: format!("unexpected trailing token {:?}", token.kind),
+                line: token.line,
+                column: token.column,
+            });
+        }
+        Ok(concept)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_multi_char_identifiers() {
+        let tokens = tokenize("some hasChild Person").unwrap();
+        assert_eq!(tokens[1].kind, TokenKind::Ident("hasChild".to_string()));
+        assert_eq!(tokens[2].kind, TokenKind::Ident("Person".to_string()));
+    }
+
+    #[test]
+    fn test_tokenize_reports_line_and_column_of_bad_input() {
+        let err = tokenize("A\n(not %B)").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.column, 6);
+    }
+
+    #[test]
+    fn test_parse_concept_reports_error_instead_of_panicking_on_eof() {
+        let tokens = tokenize("and").unwrap();
+        let err = Parser::new(&tokens).parse_concept_to_end().unwrap_err();
+        assert!(err.message.contains("parenthesized operand"));
+    }
+}
@@ -0,0 +1,104 @@
+#[macro_use]
+extern crate mopa;
+#[macro_use]
+extern crate log;
+
+mod common;
+mod lexer;
+mod abox;
+mod tbox;
+mod reasoner;
+
+// tbox.rs refers to this module as `concept`; alias it here rather than
+// renaming the file.
+use common as concept;
+
+use std::env;
+use std::io::{self, BufRead, Write};
+
+use abox::{ABox, ABoxAxiom, ConceptAxiom, RelationAxiom, parse_abox_axiom};
+use tbox::{TBox, parse_tbox_axiom};
+use reasoner::is_consistent;
+use concept::format_concept_indented;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.iter().any(|a| a == "-r" || a == "--repl") {
+        run_repl();
+    } else {
+        println!("Usage: dl-reasoner --repl");
+    }
+}
+
+// Keeps a live TBox/ABox across lines of input instead of re-parsing a whole
+// file each time, so experimenting with a knowledge base is interactive.
+fn run_repl() {
+    let stdin = io::stdin();
+    let mut tbox = TBox::new();
+    let mut abox = ABox::new();
+
+    println!("dl-reasoner REPL. Enter TBox/ABox axioms, or a command (:consistent, :entails C[x], :show).");
+
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let line = line.trim();
+
+        if line.is_empty() {
+            // nothing to do
+        } else if line == ":consistent" {
+            println!("{}", if is_consistent(&abox, &tbox) { "consistent" } else { "inconsistent" });
+        } else if line.starts_with(":entails") {
+            handle_entails(&line[":entails".len()..], &abox, &tbox);
+        } else if line == ":show" {
+            println!("{}", tbox);
+            println!("ABox:");
+            for axiom in &abox.axioms {
+                if let Some(concept_axiom) = axiom.downcast_ref::<ConceptAxiom>() {
+                    println!("  - {}[{}]", format_concept_indented(&*concept_axiom.concept, 0), concept_axiom.individual.name);
+                } else if let Some(relation_axiom) = axiom.downcast_ref::<RelationAxiom>() {
+                    println!("  - {}[{},{}]", relation_axiom.relation.name, relation_axiom.lhs.name, relation_axiom.rhs.name);
+                } else {
+                    println!("  - {:?}", axiom);
+                }
+            }
+        } else if line.contains("==") || line.contains("->") {
+            match parse_tbox_axiom(line) {
+                Ok(axiom) => { tbox.insert(axiom); println!("{}", tbox); },
+                Err(e) => println!("parse error: {}", e),
+            }
+        } else {
+            match parse_abox_axiom(line) {
+                Ok(axiom) => { abox.axioms.insert(axiom); println!("ABox:\n  - {:?}", abox.axioms); },
+                Err(e) => println!("parse error: {}", e),
+            }
+        }
+
+        io::stdout().flush().unwrap();
+    }
+}
+
+// Checks whether `x` must be an instance of `C` by adding `not C[x]` to a
+// scratch copy of the ABox and testing whether that makes it inconsistent.
+fn handle_entails(axiom_str: &str, abox: &ABox, tbox: &TBox) {
+    let axiom = match parse_abox_axiom(axiom_str.trim()) {
+        Ok(axiom) => axiom,
+        Err(e) => { println!("parse error: {}", e); return; },
+    };
+
+    match axiom.downcast_ref::<ConceptAxiom>() {
+        Some(concept_axiom) => {
+            let mut candidate = abox.clone();
+            candidate.axioms.insert(Box::new(ConceptAxiom {
+                concept: concept_axiom.concept.negate().convert_to_nnf(),
+                individual: concept_axiom.individual.clone(),
+            }));
+
+            println!("{}", if is_consistent(&candidate, tbox) { "not entailed" } else { "entailed" });
+        },
+        None => println!(":entails expects a concept assertion, e.g. ':entails C[x]'"),
+    }
+}
@@ -0,0 +1,496 @@
+/*
+    Tableau-based consistency checker for ABoxes under a TBox (ALC + GCIs).
+
+    The algorithm builds a completion graph: individuals are nodes labelled
+    with the (NNF) concepts they must satisfy, and relation axioms together
+    with generated successors form the edges. Expansion applies the usual
+    completion rules until either a clash is found on every branch (the
+    ABox is inconsistent) or a clash-free, rule-saturated graph is reached
+    (the witness model).
+*/
+use std::collections::{HashMap, HashSet};
+
+use abox::{ABox, ABoxAxiom, ABoxAxiomType, ConceptAxiom, RelationAxiom};
+use common::{Concept, NotConcept, ConjunctionConcept, DisjunctionConcept, OnlyConcept, SomeConcept,
+             MinCardinality, MaxCardinality, Relation};
+use tbox::TBox;
+
+#[derive(Debug, Clone)]
+pub struct ReasonerNode {
+    pub name: String,
+    pub label: HashSet<Box<dyn Concept>>,
+    pub parent: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompletionGraph {
+    pub nodes: HashMap<String, ReasonerNode>,
+    pub edges: Vec<(String, Relation, String)>,
+    // Pairs of individuals forced distinct by the >=-rule (unordered, so
+    // always stored with the lexicographically smaller name first).
+    differences: HashSet<(String, String)>,
+    fresh_counter: usize,
+}
+
+fn normalize_pair(a: String, b: String) -> (String, String) {
+    if a <= b { (a, b) } else { (b, a) }
+}
+
+impl CompletionGraph {
+    fn new() -> CompletionGraph {
+        CompletionGraph { nodes: HashMap::new(), edges: Vec::new(), differences: HashSet::new(), fresh_counter: 0 }
+    }
+
+    fn node_names(&self) -> Vec<String> {
+        self.nodes.keys().cloned().collect()
+    }
+
+    fn fresh_individual(&mut self) -> String {
+        self.fresh_counter += 1;
+        format!("_gen{}", self.fresh_counter)
+    }
+
+    fn successors(&self, name: &str) -> Vec<(Relation, String)> {
+        self.edges.iter()
+            .filter(|&&(ref from, _, _)| from == name)
+            .map(|&(_, ref relation, ref to)| (relation.clone(), to.clone()))
+            .collect()
+    }
+
+    fn ancestors(&self, name: &str) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut current = self.nodes.get(name).and_then(|n| n.parent.clone());
+
+        while let Some(ancestor) = current {
+            current = self.nodes.get(&ancestor).and_then(|n| n.parent.clone());
+            chain.push(ancestor);
+        }
+
+        chain
+    }
+
+    // Subset blocking: x is blocked if some ancestor y has label(x) subset of label(y).
+    fn is_blocked(&self, name: &str) -> bool {
+        let label = &self.nodes[name].label;
+
+        self.ancestors(name).iter().any(|ancestor| {
+            label.is_subset(&self.nodes[ancestor].label)
+        })
+    }
+
+    fn has_clash(&self, name: &str) -> bool {
+        let label = &self.nodes[name].label;
+
+        label.iter().any(|concept| {
+            match concept.downcast_ref::<NotConcept>() {
+                Some(not_concept) => label.contains(not_concept.subconcept()),
+                None => false,
+            }
+        })
+    }
+
+    fn mark_different(&mut self, a: &str, b: &str) {
+        self.differences.insert(normalize_pair(a.to_string(), b.to_string()));
+    }
+
+    fn are_different(&self, a: &str, b: &str) -> bool {
+        self.differences.contains(&normalize_pair(a.to_string(), b.to_string()))
+    }
+
+    // r-successors of `name` whose label contains `concept`, deduplicated by
+    // target name -- `merge` can leave two edges pointing at the same
+    // successor, and counting it twice would make the <=-rule fire forever.
+    fn successors_in(&self, name: &str, relation: &Relation, concept: &Box<dyn Concept>) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.successors(name).into_iter()
+            .filter(|&(ref r, ref to)| r.name == relation.name && self.nodes[to].label.contains(concept))
+            .map(|(_, to)| to)
+            .filter(|to| seen.insert(to.clone()))
+            .collect()
+    }
+
+    // Greedily picks a maximal pairwise-different subset of `candidates`.
+    fn pairwise_different_subset(&self, candidates: &[String]) -> Vec<String> {
+        let mut chosen: Vec<String> = Vec::new();
+        for candidate in candidates {
+            if chosen.iter().all(|other| self.are_different(candidate, other)) {
+                chosen.push(candidate.clone());
+            }
+        }
+        chosen
+    }
+
+    // Merges `drop` into `keep`: union their labels, redirect every edge and
+    // parent pointer, and drop `drop` from the graph. Used by the <=-rule.
+    fn merge(&mut self, keep: &str, drop: &str) {
+        if keep == drop {
+            return;
+        }
+
+        if let Some(drop_node) = self.nodes.remove(drop) {
+            let keep_node = self.nodes.get_mut(keep).unwrap();
+            for concept in drop_node.label {
+                keep_node.label.insert(concept);
+            }
+        }
+
+        for edge in &mut self.edges {
+            if edge.0 == drop {
+                edge.0 = keep.to_string();
+            }
+            if edge.2 == drop {
+                edge.2 = keep.to_string();
+            }
+        }
+
+        for node in self.nodes.values_mut() {
+            if node.parent.as_ref().map(|p| p.as_str() == drop).unwrap_or(false) {
+                node.parent = Some(keep.to_string());
+            }
+        }
+
+        let old_differences: Vec<(String, String)> = self.differences.drain().collect();
+        for (a, b) in old_differences {
+            let a = if a == drop { keep.to_string() } else { a };
+            let b = if b == drop { keep.to_string() } else { b };
+            if a != b {
+                self.differences.insert(normalize_pair(a, b));
+            }
+        }
+    }
+}
+
+/// Decides whether `abox` is consistent with respect to `tbox`.
+pub fn is_consistent(abox: &ABox, tbox: &TBox) -> bool {
+    find_model(abox, tbox).is_some()
+}
+
+/// Like `is_consistent`, but also returns a completed, clash-free completion
+/// graph as a witness model when the ABox is consistent.
+pub fn find_model(abox: &ABox, tbox: &TBox) -> Option<CompletionGraph> {
+    let mut graph = CompletionGraph::new();
+    let gci = tbox.aggregate_inclusions().map(|c| Box::new(c) as Box<dyn Concept>);
+
+    seed_graph(&mut graph, abox, &gci);
+
+    if expand(&mut graph, &gci) {
+        Some(graph)
+    } else {
+        None
+    }
+}
+
+fn ensure_node(graph: &mut CompletionGraph, name: &str) {
+    graph.nodes.entry(name.to_string()).or_insert_with(|| ReasonerNode {
+        name: name.to_string(),
+        label: HashSet::new(),
+        parent: None,
+    });
+}
+
+fn seed_graph(graph: &mut CompletionGraph, abox: &ABox, gci: &Option<Box<dyn Concept>>) {
+    for axiom in &abox.axioms {
+        match axiom.axiom_type() {
+            ABoxAxiomType::Concept => {
+                let axiom = axiom.downcast_ref::<ConceptAxiom>().unwrap();
+                ensure_node(graph, &axiom.individual.name);
+                graph.nodes.get_mut(&axiom.individual.name).unwrap()
+                    .label.insert(axiom.concept.convert_to_nnf());
+            },
+            ABoxAxiomType::Relation => {
+                let axiom = axiom.downcast_ref::<RelationAxiom>().unwrap();
+                ensure_node(graph, &axiom.lhs.name);
+                ensure_node(graph, &axiom.rhs.name);
+                graph.edges.push((axiom.lhs.name.clone(), axiom.relation.clone(), axiom.rhs.name.clone()));
+            },
+        }
+    }
+
+    // The aggregated GCI concept C_T has to hold at every individual.
+    if let Some(ref c) = *gci {
+        for name in graph.node_names() {
+            graph.nodes.get_mut(&name).unwrap().label.insert(c.clone());
+        }
+    }
+}
+
+// Applies completion rules until the graph is saturated (returns true) or
+// every branch clashes (returns false). The disjunction rule backtracks by
+// cloning the graph per branch, which is simple rather than fast but keeps
+// the implementation easy to follow.
+fn expand(graph: &mut CompletionGraph, gci: &Option<Box<dyn Concept>>) -> bool {
+    for name in graph.node_names() {
+        if graph.has_clash(&name) {
+            return false;
+        }
+    }
+
+    // sqcap-rule: add every conjunct of a conjunction already in the label.
+    for name in graph.node_names() {
+        let conjuncts: Vec<Box<dyn Concept>> = graph.nodes[&name].label.iter()
+            .filter_map(|c| c.downcast_ref::<ConjunctionConcept>())
+            .flat_map(|c| c.subconcepts().clone())
+            .collect();
+
+        let node = graph.nodes.get_mut(&name).unwrap();
+        let mut grew = false;
+        for conjunct in conjuncts {
+            if node.label.insert(conjunct) {
+                grew = true;
+            }
+        }
+
+        if grew {
+            return expand(graph, gci);
+        }
+    }
+
+    // sqcup-rule: nondeterministically branch on an undecided disjunction.
+    for name in graph.node_names() {
+        let disjunction = graph.nodes[&name].label.iter()
+            .filter_map(|c| c.downcast_ref::<DisjunctionConcept>())
+            .find(|d| !d.subconcepts().iter().any(|d| graph.nodes[&name].label.contains(d)))
+            .cloned();
+
+        if let Some(disjunction) = disjunction {
+            for disjunct in disjunction.subconcepts() {
+                let mut branch = graph.clone();
+                branch.nodes.get_mut(&name).unwrap().label.insert(disjunct.clone());
+
+                if expand(&mut branch, gci) {
+                    *graph = branch;
+                    return true;
+                }
+            }
+
+            return false; // every disjunct led to a clash
+        }
+    }
+
+    // exists-rule: generate a fresh successor, unless the node is blocked.
+    for name in graph.node_names() {
+        if graph.is_blocked(&name) {
+            continue;
+        }
+
+        let some_concept = graph.nodes[&name].label.iter()
+            .filter_map(|c| c.downcast_ref::<SomeConcept>())
+            .find(|s| {
+                !graph.successors(&name).iter().any(|&(ref r, ref to)| {
+                    r.name == s.relation().name && graph.nodes[to].label.contains(s.subconcept())
+                })
+            })
+            .cloned();
+
+        if let Some(some_concept) = some_concept {
+            let successor = graph.fresh_individual();
+            let mut label = HashSet::new();
+            label.insert(some_concept.subconcept().clone());
+            if let Some(ref c) = *gci {
+                label.insert(c.clone());
+            }
+
+            graph.nodes.insert(successor.clone(), ReasonerNode {
+                name: successor.clone(),
+                label: label,
+                parent: Some(name.clone()),
+            });
+            graph.edges.push((name.clone(), some_concept.relation().clone(), successor));
+
+            return expand(graph, gci);
+        }
+    }
+
+    // forall-rule: propagate the filler to every existing relation-successor.
+    for name in graph.node_names() {
+        let only_concepts: Vec<Box<OnlyConcept>> = graph.nodes[&name].label.iter()
+            .filter_map(|c| c.downcast_ref::<OnlyConcept>())
+            .cloned()
+            .map(Box::new)
+            .collect();
+
+        for only_concept in only_concepts {
+            for (relation, successor) in graph.successors(&name) {
+                if relation.name == only_concept.relation().name {
+                    let grew = graph.nodes.get_mut(&successor).unwrap()
+                        .label.insert(only_concept.subconcept().clone());
+
+                    if grew {
+                        return expand(graph, gci);
+                    }
+                }
+            }
+        }
+    }
+
+    // >=-rule: generate fresh, pairwise-different r-successors in C until
+    // there are at least n of them, unless the node is blocked.
+    for name in graph.node_names() {
+        if graph.is_blocked(&name) {
+            continue;
+        }
+
+        let min_concept = graph.nodes[&name].label.iter()
+            .filter_map(|c| c.downcast_ref::<MinCardinality>())
+            .find(|m| {
+                let candidates = graph.successors_in(&name, m.relation(), m.subconcept());
+                graph.pairwise_different_subset(&candidates).len() < m.n()
+            })
+            .cloned();
+
+        if let Some(min_concept) = min_concept {
+            let candidates = graph.successors_in(&name, min_concept.relation(), min_concept.subconcept());
+            let mut distinct = graph.pairwise_different_subset(&candidates);
+
+            while distinct.len() < min_concept.n() {
+                let successor = graph.fresh_individual();
+                let mut label = HashSet::new();
+                label.insert(min_concept.subconcept().clone());
+                if let Some(ref c) = *gci {
+                    label.insert(c.clone());
+                }
+
+                graph.nodes.insert(successor.clone(), ReasonerNode {
+                    name: successor.clone(),
+                    label: label,
+                    parent: Some(name.clone()),
+                });
+                graph.edges.push((name.clone(), min_concept.relation().clone(), successor.clone()));
+
+                for other in &distinct {
+                    graph.mark_different(&successor, other);
+                }
+                distinct.push(successor);
+            }
+
+            return expand(graph, gci);
+        }
+    }
+
+    // <=-rule: if there are more than n r-successors in C, merge two of them
+    // that aren't forced distinct. Like the sqcup-rule, this is a
+    // nondeterministic choice -- the first legal pair to merge cleanly isn't
+    // necessarily the one that leads to a model, so each candidate pair is
+    // tried on its own cloned branch and we backtrack to the next pair if a
+    // merge's branch clashes. If every pair is forced distinct (or every
+    // legal merge still clashes), the bound can never be satisfied here.
+    for name in graph.node_names() {
+        let max_concept = graph.nodes[&name].label.iter()
+            .filter_map(|c| c.downcast_ref::<MaxCardinality>())
+            .find(|m| graph.successors_in(&name, m.relation(), m.subconcept()).len() > m.n())
+            .cloned();
+
+        if let Some(max_concept) = max_concept {
+            let candidates = graph.successors_in(&name, max_concept.relation(), max_concept.subconcept());
+            let mergeable_pairs = candidates.iter().enumerate()
+                .flat_map(|(i, a)| candidates[i + 1..].iter().map(move |b| (a.clone(), b.clone())))
+                .filter(|&(ref a, ref b)| a != b && !graph.are_different(a, b));
+
+            for (keep, drop) in mergeable_pairs {
+                let mut branch = graph.clone();
+                branch.merge(&keep, &drop);
+
+                if expand(&mut branch, gci) {
+                    *graph = branch;
+                    return true;
+                }
+            }
+
+            return false; // no legal merge (or every one of them) led to a model
+        }
+    }
+
+    true // no rule applies anywhere and no node clashed: complete and consistent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abox::parse_abox;
+    use tbox::parse_tbox;
+
+    fn check(abox_str: &str, tbox_str: &str) -> bool {
+        let (abox, abox_errors) = parse_abox(abox_str);
+        let (tbox, tbox_errors) = parse_tbox(tbox_str);
+        assert!(abox_errors.is_empty(), "{:?}", abox_errors);
+        assert!(tbox_errors.is_empty(), "{:?}", tbox_errors);
+        is_consistent(&abox, &tbox)
+    }
+
+    #[test]
+    fn test_direct_clash_is_inconsistent() {
+        assert!(!check("C[x]\nnot C[x]", ""));
+    }
+
+    #[test]
+    fn test_satisfiable_abox_is_consistent() {
+        assert!(check("C[x]\nD[x]", ""));
+    }
+
+    #[test]
+    fn test_gci_forces_a_clash() {
+        // Every C is D, but x is asserted C and not D.
+        assert!(!check("C[x]\nnot D[x]", "C -> D"));
+    }
+
+    #[test]
+    fn test_existential_plus_blocking_terminates() {
+        // "C -> some r C" would generate an unbounded chain of fresh
+        // r-successors without blocking; with it, expansion must still
+        // terminate (and the ABox is satisfiable: x can see itself).
+        assert!(check("C[x]", "C -> (some r C)"));
+    }
+
+    #[test]
+    fn test_max_cardinality_merges_successors_and_terminates() {
+        // x has two r-successors in C, but is bounded to at most one --
+        // the <=-rule must merge y and z (rather than looping forever
+        // re-finding the same pair) and the result is consistent.
+        assert!(check("(<= 1 r C)[x]\nr[x,y]\nr[x,z]\nC[y]\nC[z]", ""));
+    }
+
+    #[test]
+    fn test_max_cardinality_violated_by_distinct_individuals_is_inconsistent() {
+        // Same bound, but y and z are asserted distinct (via a clashing
+        // concept each only one of them has), so they cannot be merged.
+        assert!(!check("(<= 1 r C)[x]\nr[x,y]\nr[x,z]\nC[y]\nC[z]\nD[y]\nnot D[z]", ""));
+    }
+
+    #[test]
+    fn test_disjunction_backtracks_to_the_satisfiable_disjunct() {
+        // x is "A or B", and A clashes immediately (not A[x] also holds), so
+        // the sqcup-rule must backtrack off the first disjunct it tries and
+        // pick B instead rather than giving up on the first clash.
+        assert!(check("(or (A) (B))[x]\nnot A[x]", ""));
+    }
+
+    #[test]
+    fn test_disjunction_with_no_satisfiable_branch_is_inconsistent() {
+        assert!(!check("(or (A) (B))[x]\nnot A[x]\nnot B[x]", ""));
+    }
+
+    #[test]
+    fn test_forall_propagates_filler_to_existing_successor_and_clashes() {
+        // x is "only r C" and has an r-successor y that is "not C" -- the
+        // forall-rule must propagate C into y's label, clashing with the
+        // "not C" already there.
+        assert!(!check("(only r C)[x]\nr[x,y]\nnot C[y]", ""));
+    }
+
+    #[test]
+    fn test_forall_propagates_filler_to_existing_successor_and_stays_consistent() {
+        assert!(check("(only r C)[x]\nr[x,y]\nD[y]", ""));
+    }
+
+    #[test]
+    fn test_max_cardinality_backtracks_past_clashing_merges_to_a_safe_one() {
+        // x is bounded to at most 2 r-successors in C but has three: y, z, w.
+        // Merging y with w (or z with w) clashes, but merging y with z does
+        // not -- the <=-rule must try the other candidate pairs rather than
+        // committing to (and clashing on) whichever pair it finds first.
+        assert!(check(
+            "(<= 2 r C)[x]\nr[x,y]\nr[x,z]\nr[x,w]\nC[y]\nC[z]\nC[w]\nD[y]\nnot D[w]\nE[z]\nnot E[w]",
+            ""
+        ));
+    }
+}
@@ -1,41 +1,62 @@
 use std::fmt;
 use std::hash;
 use std::collections::HashSet;
-use std::iter::FromIterator;
 
-use abox::{ABox, ABoxAxiom, ABoxAxiomType, ConceptAxiom};
-use concept::{Concept, ConjunctionConcept, DisjunctionConcept, parse_concept};
+use concept::{Concept, ConjunctionConcept, DisjunctionConcept};
+use lexer::{tokenize, Parser, ParseError, TokenKind};
 
 
-pub fn parse_tbox(tbox_str: &str) -> TBox {
+/// Parses every non-comment, non-blank line as a TBox axiom, collecting
+/// all parse errors instead of aborting on the first one.
+pub fn parse_tbox(tbox_str: &str) -> (TBox, Vec<ParseError>) {
     debug!("Parsing TBox!");
 
-    let tbox_str = tbox_str.trim();
     let mut tbox = TBox::new();
+    let mut errors = Vec::new();
 
-    for line in tbox_str.lines() {
+    for line in tbox_str.trim().lines() {
         debug!("Parsing line: {}", line);
 
+        let line = line.trim();
         if line.len() > 0 && !line.starts_with('#') {
-            tbox.axioms.insert(Box::new(parse_tbox_axiom(line)));
+            match parse_tbox_axiom(line) {
+                Ok(axiom) => tbox.insert(axiom),
+                Err(e) => errors.push(e),
+            }
         }
     }
 
-    tbox
+    (tbox, errors)
 }
 
 
-pub fn parse_tbox_axiom(tbox_line: &str) -> TBoxAxiom {
-    let tbox_line = tbox_line.trim();
-    let delimiter = if tbox_line.contains("==") { "==" } else { "->" };
-    let axiom_type = if delimiter == "==" { TBoxAxiomType::Definition } else { TBoxAxiomType::Inclusion };
-    let delimiter_idx = tbox_line.find(delimiter).unwrap();
+pub fn parse_tbox_axiom(tbox_line: &str) -> Result<TBoxAxiom, ParseError> {
+    let tokens = tokenize(tbox_line)?;
+    let delimiter_idx = tokens.iter()
+        .position(|t| t.kind == TokenKind::DoubleEquals || t.kind == TokenKind::Arrow)
+        .ok_or_else(|| {
+            let (line, column) = tokens.last().map(|t| (t.line, t.column)).unwrap_or((1, 1));
+            ParseError {
+                message: "expected '==' or '->' in TBox axiom".to_string(),
+                line,
+                column,
+            }
+        })?;
+
+    let axiom_type = if tokens[delimiter_idx].kind == TokenKind::DoubleEquals {
+        TBoxAxiomType::Definition
+    } else {
+        TBoxAxiomType::Inclusion
+    };
+
+    let lhs = Parser::new(&tokens[..delimiter_idx]).parse_concept_to_end()?;
+    let rhs = Parser::new(&tokens[delimiter_idx + 1..]).parse_concept_to_end()?;
 
-    TBoxAxiom {
+    Ok(TBoxAxiom {
         axiom_type: axiom_type,
-        lhs: parse_concept(&tbox_line[..delimiter_idx]).convert_to_nnf(),
-        rhs: parse_concept(&tbox_line[delimiter_idx + 2..]).convert_to_nnf()
-    }
+        lhs: lhs.convert_to_nnf(),
+        rhs: rhs.convert_to_nnf(),
+    })
 }
 
 #[derive(Debug, Clone)]
@@ -47,102 +68,33 @@ impl TBox {
     pub fn new() -> TBox {
         TBox {axioms: HashSet::new()}
     }
-}
-
-impl TBox {
-    pub fn expand_all_definitions(&mut self) {
-        info!("Expanding TBox definitions...");
-        // Expands all the definitions in such a way that we do not use
-        // definitions inside definitions
-        let mut definitions = self.axioms.clone().into_iter()
-            .filter(|a| a.axiom_type == TBoxAxiomType::Definition)
-            .collect::<Vec<Box<TBoxAxiom>>>();
-        let mut definitions_updated = definitions.clone();
-        let mut applied_defs = HashSet::new();
-
-        while let Some(def) = definitions.pop() {
-            applied_defs.insert(def.lhs.clone());
-            // Expanding the definition in all the possible definitions
-            // After that we will not have this definition anywhere except for itself
-            definitions_updated = definitions_updated
-                .into_iter()
-                .clone()
-                .map(|d| {
-                    if def.lhs.to_string() == d.lhs.to_string() {
-                        Box::new(*d)
-                    } else {
-                        Box::new(TBoxAxiom {
-                            axiom_type: d.axiom_type.clone(),
-                            lhs: d.lhs.clone(),
-                            rhs: d.rhs.replace_concept(def.lhs.clone(), def.rhs.clone())
-                        })
-                    }
-                })
-                .collect::<Vec<Box<TBoxAxiom>>>();
-
-            definitions = definitions_updated.clone()
-                .into_iter()
-                .filter(|d| {!applied_defs.contains(&d.lhs)})
-                .collect();
-        }
-    }
-
-    pub fn apply_definitions_to_abox(&self, abox: &mut ABox) {
-        info!("Applying expanded TBox definitions to an ABox...");
-        let definitions = self.axioms.clone().into_iter()
-            .filter(|a| a.axiom_type == TBoxAxiomType::Definition)
-            .collect::<Vec<Box<TBoxAxiom>>>();
-
-        abox.axioms = HashSet::from_iter(abox.axioms.clone().iter().map(|a| {
-            match a.axiom_type() {
-                ABoxAxiomType::Relation => a.clone(),
-                ABoxAxiomType::Concept => {
-                    let axiom = a.downcast_ref::<ConceptAxiom>().unwrap();
-                    let replaced_concept = definitions.clone()
-                        .iter()
-                        .fold(axiom.concept.clone(), |c, def| {
-                            c.replace_concept(def.lhs.clone(), def.rhs.clone())
-                        });
-
-                    Box::new(ConceptAxiom {
-                        concept: replaced_concept,
-                        individual: axiom.individual.clone()
-                    }) as Box<dyn ABoxAxiom>
-                },
-            }
-        }))
-    }
 
-    pub fn apply_definitions_to_inclusions(&mut self) {
-        info!("Applying expanded TBox definitions to GCIs...");
-        let definitions = self.axioms.clone().into_iter()
-            .filter(|a| a.axiom_type == TBoxAxiomType::Definition)
-            .collect::<Vec<Box<TBoxAxiom>>>();
-        let mut inclusions = self.axioms.clone().into_iter()
-            .filter(|a| a.axiom_type == TBoxAxiomType::Inclusion)
-            .collect::<Vec<Box<TBoxAxiom>>>();
-
-        for inclusion in &mut inclusions {
-            for def in &definitions {
-                inclusion.lhs = inclusion.lhs.replace_concept(def.lhs.clone(), def.rhs.clone());
-                inclusion.rhs = inclusion.rhs.replace_concept(def.lhs.clone(), def.rhs.clone());
-            }
-        }
+    pub fn insert(&mut self, axiom: TBoxAxiom) {
+        self.axioms.insert(Box::new(axiom));
     }
+}
 
+impl TBox {
+    // Aggregates every GCI into a single C_T that has to hold everywhere.
+    // A definition "A == B" is folded in as two GCIs (A -> B and B -> A)
+    // rather than requiring callers to expand definitions separately --
+    // otherwise the tableau never sees what a "==" axiom actually entails.
     pub fn aggregate_inclusions(&self) -> Option<ConjunctionConcept> {
         info!("Aggregating GCIs into a single one...");
-        let inclusions = self.axioms.clone().into_iter()
-            .filter(|a| a.axiom_type == TBoxAxiomType::Inclusion)
-            .collect::<Vec<Box<TBoxAxiom>>>();
-
-        if inclusions.is_empty() {
+        let gcis = self.axioms.clone().into_iter()
+            .flat_map(|a| match a.axiom_type {
+                TBoxAxiomType::Inclusion => vec![(a.lhs.clone(), a.rhs.clone())],
+                TBoxAxiomType::Definition => vec![(a.lhs.clone(), a.rhs.clone()), (a.rhs.clone(), a.lhs.clone())],
+            })
+            .collect::<Vec<(Box<dyn Concept>, Box<dyn Concept>)>>();
+
+        if gcis.is_empty() {
             return None;
         }
 
-        let subconcepts = inclusions
+        let subconcepts = gcis
             .into_iter()
-            .map(|ta| {DisjunctionConcept {subconcepts: vec![ta.lhs.negate().convert_to_nnf(), ta.rhs]}})
+            .map(|(lhs, rhs)| {DisjunctionConcept {subconcepts: vec![lhs.negate().convert_to_nnf(), rhs]}})
             .map(|a| Box::new(a) as Box<dyn Concept>)
             .collect::<Vec<Box<dyn Concept>>>();
 
@@ -187,3 +139,14 @@ impl hash::Hash for TBoxAxiom {
         self.to_string().hash(hasher);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_delimiter_error_points_at_the_last_token_not_at_1_1() {
+        let err = parse_tbox_axiom("A B").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 3));
+    }
+}